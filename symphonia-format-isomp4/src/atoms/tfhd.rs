@@ -0,0 +1,103 @@
+// Symphonia
+// Copyright (c) 2020 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::Result;
+use symphonia_core::io::ByteStream;
+
+use crate::atoms::{Atom, AtomHeader};
+
+/// Track fragment header atom.
+#[derive(Debug)]
+pub struct TfhdAtom {
+    /// Atom header.
+    header: AtomHeader,
+    /// Extended header flags.
+    flags: u32,
+    /// The track ID this fragment belongs to.
+    pub track_id: u32,
+    /// The base data offset for samples in this track fragment, if explicitly provided.
+    pub base_data_offset: Option<u64>,
+    /// An override of the sample description index used by this track fragment.
+    pub sample_description_index: Option<u32>,
+    /// An override of the default sample duration used by this track fragment.
+    pub default_sample_duration: Option<u32>,
+    /// An override of the default sample size used by this track fragment.
+    pub default_sample_size: Option<u32>,
+    /// An override of the default sample flags used by this track fragment.
+    pub default_sample_flags: Option<u32>,
+}
+
+impl TfhdAtom {
+    const BASE_DATA_OFFSET_PRESENT: u32 = 0x1;
+    const SAMPLE_DESCRIPTION_INDEX_PRESENT: u32 = 0x2;
+    const DEFAULT_SAMPLE_DURATION_PRESENT: u32 = 0x8;
+    const DEFAULT_SAMPLE_SIZE_PRESENT: u32 = 0x10;
+    const DEFAULT_SAMPLE_FLAGS_PRESENT: u32 = 0x20;
+    /// Indicates that there are no samples for this time interval in this track fragment.
+    const DURATION_IS_EMPTY: u32 = 0x1_0000;
+    /// Indicates the default base for offsets is the start of the enclosing `moof`, not the
+    /// previous `moof` or `mdat`.
+    const DEFAULT_BASE_IS_MOOF: u32 = 0x2_0000;
+
+    /// Indicates if this track fragment declares no samples for this time interval.
+    pub fn is_duration_empty(&self) -> bool {
+        self.flags & TfhdAtom::DURATION_IS_EMPTY != 0
+    }
+
+    /// Indicates if the default base data offset is the start of the enclosing `moof` atom.
+    pub fn is_default_base_moof(&self) -> bool {
+        self.flags & TfhdAtom::DEFAULT_BASE_IS_MOOF != 0
+    }
+}
+
+impl Atom for TfhdAtom {
+    fn header(&self) -> AtomHeader {
+        self.header
+    }
+
+    fn read<B: ByteStream>(reader: &mut B, header: AtomHeader) -> Result<Self> {
+        let (_, flags) = AtomHeader::read_extra(reader)?;
+
+        let track_id = reader.read_be_u32()?;
+
+        let base_data_offset = match flags & TfhdAtom::BASE_DATA_OFFSET_PRESENT {
+            0 => None,
+            _ => Some(reader.read_be_u64()?),
+        };
+
+        let sample_description_index = match flags & TfhdAtom::SAMPLE_DESCRIPTION_INDEX_PRESENT {
+            0 => None,
+            _ => Some(reader.read_be_u32()?),
+        };
+
+        let default_sample_duration = match flags & TfhdAtom::DEFAULT_SAMPLE_DURATION_PRESENT {
+            0 => None,
+            _ => Some(reader.read_be_u32()?),
+        };
+
+        let default_sample_size = match flags & TfhdAtom::DEFAULT_SAMPLE_SIZE_PRESENT {
+            0 => None,
+            _ => Some(reader.read_be_u32()?),
+        };
+
+        let default_sample_flags = match flags & TfhdAtom::DEFAULT_SAMPLE_FLAGS_PRESENT {
+            0 => None,
+            _ => Some(reader.read_be_u32()?),
+        };
+
+        Ok(TfhdAtom {
+            header,
+            flags,
+            track_id,
+            base_data_offset,
+            sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+        })
+    }
+}
@@ -0,0 +1,275 @@
+// Symphonia
+// Copyright (c) 2020 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::{Result, decode_error};
+use symphonia_core::io::ByteStream;
+
+use crate::atoms::tfhd::TfhdAtom;
+use crate::atoms::trex::TrexAtom;
+use crate::atoms::trun::TrunAtom;
+use crate::atoms::{Atom, AtomHeader, AtomIterator, AtomType};
+
+/// A single sample of a track fragment, fully resolved against the `tfhd` overrides and the
+/// track's `trex` defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct TrafSample {
+    /// The byte position of the sample in the stream. This is absolute only when the `tfhd`
+    /// provides a `base_data_offset`; otherwise it is counted from a base of 0.
+    pub pos: u64,
+    /// The size of the sample, in bytes.
+    pub size: u32,
+    /// The duration of the sample, in the track's timescale.
+    pub duration: u32,
+    /// The sample flags (e.g., whether the sample is a sync sample).
+    pub flags: u32,
+    /// The composition time offset of the sample.
+    pub composition_time_offset: i64,
+}
+
+/// Track fragment atom.
+#[derive(Debug)]
+pub struct TrafAtom {
+    /// Atom header.
+    header: AtomHeader,
+    /// Track fragment header, overriding the `trex` defaults for this fragment.
+    pub tfhd: TfhdAtom,
+    /// The runs of samples belonging to this track fragment, in file order. A `traf` may split
+    /// its samples across several `trun` atoms, e.g. when interleaving streams or when a new
+    /// `data_offset` is needed.
+    pub truns: Vec<TrunAtom>,
+}
+
+impl TrafAtom {
+    /// Resolves the samples of this track fragment's `trun`s against the `tfhd` overrides and the
+    /// track's `trex` defaults, yielding a single, contiguous, correctly-offset stream of samples
+    /// across all runs.
+    pub fn samples(&self, trex: &TrexAtom) -> Result<Vec<TrafSample>> {
+        let default_sample_duration =
+            self.tfhd.default_sample_duration.unwrap_or(trex.default_sample_duration);
+        let default_sample_size = self.tfhd.default_sample_size.unwrap_or(trex.default_sample_size);
+        let default_sample_flags =
+            self.tfhd.default_sample_flags.unwrap_or(trex.default_sample_flags);
+
+        // The base data offset is the explicit tfhd base_data_offset, or 0 when the tfhd does not
+        // provide one. In the latter case the position of the enclosing moof is not known here,
+        // the default-base-is-moof flag is not consulted, and the resulting positions are not
+        // absolute. A trun's explicit
+        // data_offset, if present, is relative to this base; otherwise the run starts right where
+        // the previous run's last sample ended.
+        let base_data_offset = self.tfhd.base_data_offset.unwrap_or(0);
+
+        let mut samples = Vec::new();
+        let mut pos = base_data_offset;
+
+        for trun in &self.truns {
+            if let Some(data_offset) = trun.data_offset {
+                let signed_pos = i64::try_from(base_data_offset)
+                    .ok()
+                    .and_then(|base| base.checked_add(i64::from(data_offset)));
+
+                pos = match signed_pos {
+                    Some(signed_pos) if signed_pos >= 0 => signed_pos as u64,
+                    _ => {
+                        return decode_error(
+                            "isomp4 (traf): trun data_offset yields a negative sample position",
+                        )
+                    }
+                };
+            }
+
+            samples.reserve(trun.sample_count as usize);
+
+            for i in 0..trun.sample_count as usize {
+                let size = if trun.is_sample_size_present() {
+                    trun.sample_size[i]
+                } else {
+                    default_sample_size
+                };
+
+                let duration = if trun.is_sample_duration_present() {
+                    trun.sample_duration[i]
+                } else {
+                    default_sample_duration
+                };
+
+                let flags = trun.sample_flags(i, default_sample_flags);
+
+                samples.push(TrafSample {
+                    pos,
+                    size,
+                    duration,
+                    flags,
+                    composition_time_offset: trun.composition_time_offset(i),
+                });
+
+                pos += u64::from(size);
+            }
+        }
+
+        Ok(samples)
+    }
+}
+
+impl Atom for TrafAtom {
+    fn header(&self) -> AtomHeader {
+        self.header
+    }
+
+    fn read<B: ByteStream>(reader: &mut B, header: AtomHeader) -> Result<Self> {
+        let mut tfhd = None;
+        let mut truns = Vec::new();
+
+        let mut iter = AtomIterator::new(reader, header);
+
+        while let Some(atom_header) = iter.next()? {
+            match atom_header.atype {
+                AtomType::TrackFragmentHeader => {
+                    tfhd = Some(iter.read_atom::<TfhdAtom>()?);
+                }
+                AtomType::TrackFragmentRun => {
+                    truns.push(iter.read_atom::<TrunAtom>()?);
+                }
+                _ => (),
+            }
+        }
+
+        let tfhd = match tfhd {
+            Some(tfhd) => tfhd,
+            None => return decode_error("isomp4 (traf): missing tfhd atom"),
+        };
+
+        if truns.is_empty() {
+            return decode_error("isomp4 (traf): missing trun atom");
+        }
+
+        Ok(TrafAtom { header, tfhd, truns })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use symphonia_core::io::BufReader;
+
+    fn atom_header(atype: AtomType, data_len: u64) -> AtomHeader {
+        AtomHeader { atype, data_len }
+    }
+
+    fn read_tfhd(buf: &[u8]) -> TfhdAtom {
+        let mut reader = BufReader::new(buf);
+        let header = atom_header(AtomType::TrackFragmentHeader, buf.len() as u64);
+        TfhdAtom::read(&mut reader, header).unwrap()
+    }
+
+    fn read_trex(buf: &[u8]) -> TrexAtom {
+        let mut reader = BufReader::new(buf);
+        let header = atom_header(AtomType::TrackExtends, buf.len() as u64);
+        TrexAtom::read(&mut reader, header).unwrap()
+    }
+
+    fn read_trun(buf: &[u8]) -> TrunAtom {
+        let mut reader = BufReader::new(buf);
+        let header = atom_header(AtomType::TrackFragmentRun, buf.len() as u64);
+        TrunAtom::read(&mut reader, header).unwrap()
+    }
+
+    // trex with default_sample_duration = 1000, default_sample_size = 42,
+    // default_sample_flags = 0x0101_0000.
+    fn trex_buf() -> Vec<u8> {
+        let mut buf = vec![0, 0, 0, 0]; // version/flags, unused by trex.
+        buf.extend_from_slice(&1u32.to_be_bytes()); // track_id
+        buf.extend_from_slice(&1u32.to_be_bytes()); // default_sample_description_index
+        buf.extend_from_slice(&1000u32.to_be_bytes()); // default_sample_duration
+        buf.extend_from_slice(&42u32.to_be_bytes()); // default_sample_size
+        buf.extend_from_slice(&0x0101_0000u32.to_be_bytes()); // default_sample_flags
+        buf
+    }
+
+    #[test]
+    fn tfhd_default_sample_flags_override_trex_default() {
+        // tfhd: base-data-offset-present (0x1) | default-sample-flags-present (0x20).
+        let mut tfhd_buf = [0, 0, 0, 0x21].to_vec();
+        tfhd_buf.extend_from_slice(&1u32.to_be_bytes()); // track_id
+        tfhd_buf.extend_from_slice(&0u64.to_be_bytes()); // base_data_offset
+        tfhd_buf.extend_from_slice(&0x0200_0000u32.to_be_bytes()); // default_sample_flags
+
+        // trun: no flags set, a single sample that falls back to tfhd/trex defaults entirely.
+        let mut trun_buf = [0, 0, 0, 0].to_vec();
+        trun_buf.extend_from_slice(&1u32.to_be_bytes()); // sample_count
+
+        let traf = TrafAtom {
+            header: atom_header(AtomType::TrackFragment, 0),
+            tfhd: read_tfhd(&tfhd_buf),
+            truns: vec![read_trun(&trun_buf)],
+        };
+
+        let samples = traf.samples(&read_trex(&trex_buf())).unwrap();
+
+        // The tfhd default_sample_flags override wins over the trex track default.
+        assert_eq!(samples[0].flags, 0x0200_0000);
+        // default_sample_size was not overridden by tfhd, so it falls back to trex.
+        assert_eq!(samples[0].size, 42);
+    }
+
+    #[test]
+    fn samples_are_contiguous_across_multiple_truns() {
+        // tfhd: base-data-offset-present (0x1), base_data_offset = 1000.
+        let mut tfhd_buf = [0, 0, 0, 0x01].to_vec();
+        tfhd_buf.extend_from_slice(&1u32.to_be_bytes()); // track_id
+        tfhd_buf.extend_from_slice(&1000u64.to_be_bytes()); // base_data_offset
+
+        // First run: data-offset-present (0x1) | sample-size-present (0x200), 2 samples.
+        let mut trun1_buf = [0, 0, 0x02, 0x01].to_vec();
+        trun1_buf.extend_from_slice(&2u32.to_be_bytes()); // sample_count
+        trun1_buf.extend_from_slice(&0i32.to_be_bytes()); // data_offset
+        trun1_buf.extend_from_slice(&10u32.to_be_bytes()); // sample_size[0]
+        trun1_buf.extend_from_slice(&20u32.to_be_bytes()); // sample_size[1]
+
+        // Second run: sample-size-present (0x200) only, no explicit data_offset, so it should
+        // continue right where the first run's last sample ended.
+        let mut trun2_buf = [0, 0, 0x02, 0x00].to_vec();
+        trun2_buf.extend_from_slice(&1u32.to_be_bytes()); // sample_count
+        trun2_buf.extend_from_slice(&30u32.to_be_bytes()); // sample_size[0]
+
+        let traf = TrafAtom {
+            header: atom_header(AtomType::TrackFragment, 0),
+            tfhd: read_tfhd(&tfhd_buf),
+            truns: vec![read_trun(&trun1_buf), read_trun(&trun2_buf)],
+        };
+
+        let samples = traf.samples(&read_trex(&trex_buf())).unwrap();
+
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].pos, 1000);
+        assert_eq!(samples[1].pos, 1010);
+        assert_eq!(samples[2].pos, 1030);
+    }
+
+    #[test]
+    fn negative_run_data_offset_is_rejected() {
+        // tfhd: base-data-offset-present (0x1), base_data_offset = 10.
+        let mut tfhd_buf = [0, 0, 0, 0x01].to_vec();
+        tfhd_buf.extend_from_slice(&1u32.to_be_bytes()); // track_id
+        tfhd_buf.extend_from_slice(&10u64.to_be_bytes()); // base_data_offset
+
+        // data-offset-present (0x1) | sample-size-present (0x200), data_offset = -20, which
+        // underflows the tfhd's base_data_offset of 10.
+        let mut trun_buf = [0, 0, 0x02, 0x01].to_vec();
+        trun_buf.extend_from_slice(&1u32.to_be_bytes()); // sample_count
+        trun_buf.extend_from_slice(&(-20i32).to_be_bytes()); // data_offset
+        trun_buf.extend_from_slice(&10u32.to_be_bytes()); // sample_size[0]
+
+        let traf = TrafAtom {
+            header: atom_header(AtomType::TrackFragment, 0),
+            tfhd: read_tfhd(&tfhd_buf),
+            truns: vec![read_trun(&trun_buf)],
+        };
+
+        assert!(traf.samples(&read_trex(&trex_buf())).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// Symphonia
+// Copyright (c) 2020 The Project Symphonia Developers.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use symphonia_core::errors::Result;
+use symphonia_core::io::ByteStream;
+
+use crate::atoms::{Atom, AtomHeader};
+
+/// Track extends atom. Declares the default sample description, duration, size, and flags a
+/// track fragment (`traf`) should fall back to when its `tfhd` does not override them.
+#[derive(Debug)]
+pub struct TrexAtom {
+    /// Atom header.
+    header: AtomHeader,
+    /// The track ID these defaults apply to.
+    pub track_id: u32,
+    /// The default sample description index.
+    pub default_sample_description_index: u32,
+    /// The default sample duration.
+    pub default_sample_duration: u32,
+    /// The default sample size.
+    pub default_sample_size: u32,
+    /// The default sample flags.
+    pub default_sample_flags: u32,
+}
+
+impl Atom for TrexAtom {
+    fn header(&self) -> AtomHeader {
+        self.header
+    }
+
+    fn read<B: ByteStream>(reader: &mut B, header: AtomHeader) -> Result<Self> {
+        let (_, _) = AtomHeader::read_extra(reader)?;
+
+        let track_id = reader.read_be_u32()?;
+        let default_sample_description_index = reader.read_be_u32()?;
+        let default_sample_duration = reader.read_be_u32()?;
+        let default_sample_size = reader.read_be_u32()?;
+        let default_sample_flags = reader.read_be_u32()?;
+
+        Ok(TrexAtom {
+            header,
+            track_id,
+            default_sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+        })
+    }
+}
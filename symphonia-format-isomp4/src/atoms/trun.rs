@@ -11,8 +11,6 @@ use symphonia_core::util::bits;
 
 use crate::atoms::{Atom, AtomHeader};
 
-use log::warn;
-
 /// Track fragment run atom.
 #[derive(Debug)]
 pub struct TrunAtom {
@@ -32,6 +30,8 @@ pub struct TrunAtom {
     pub sample_size: Vec<u32>,
     /// Sample flags for each sample in this run.
     pub sample_flags: Vec<u32>,
+    /// Sample composition time offset for each sample in this run.
+    pub sample_composition_time_offset: Vec<i64>,
     /// The total size of all samples in this run. 0 if the sample size flag is not set.
     pub total_sample_size: u64,
     /// The total duration of all samples in this run. 0 if the sample duration flag is not set.
@@ -63,6 +63,33 @@ impl TrunAtom {
     pub fn are_sample_composition_time_offsets_present(&self) -> bool {
         self.flags & TrunAtom::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT != 0
     }
+
+    /// Gets the flags that apply to the sample at index `i`, falling back to `default` (the
+    /// track's default sample flags from `tfhd`/`trex`) when neither `first-sample-flags` nor
+    /// `sample-flags` apply to that sample.
+    pub fn sample_flags(&self, i: usize, default: u32) -> u32 {
+        if i == 0 {
+            if let Some(first_sample_flags) = self.first_sample_flags {
+                return first_sample_flags;
+            }
+        }
+
+        if self.are_sample_flags_present() {
+            self.sample_flags[i]
+        } else {
+            default
+        }
+    }
+
+    /// Gets the composition time offset of the sample at index `i`, or 0 if composition time
+    /// offsets are not present.
+    pub fn composition_time_offset(&self, i: usize) -> i64 {
+        if self.are_sample_composition_time_offsets_present() {
+            self.sample_composition_time_offset[i]
+        } else {
+            0
+        }
+    }
 }
 
 impl Atom for TrunAtom {
@@ -75,7 +102,7 @@ impl Atom for TrunAtom {
         const DATA_OFFSET_PRESENT: u32 = 0x1;
         const FIRST_SAMPLE_FLAGS_PRESENT: u32 = 0x4;
 
-        let (_, flags) = AtomHeader::read_extra(reader)?;
+        let (version, flags) = AtomHeader::read_extra(reader)?;
 
         let sample_count = reader.read_be_u32()?;
 
@@ -89,11 +116,6 @@ impl Atom for TrunAtom {
             _ => Some(reader.read_be_u32()?),
         };
 
-        // Remember to implement support for truns with first-sample-flags-present.
-        if first_sample_flags.is_some() {
-            todo!("support truns with first-sample-flags-present");
-        }
-
         // If the first-sample-flags-present flag is set, then the sample-flags-present flag should
         // not be set. The samples after the first shall use the default sample flags defined in the
         // tfhd or mvex atoms.
@@ -101,14 +123,73 @@ impl Atom for TrunAtom {
             return decode_error("sample-flag-present and first-sample-flags-present flags are set");
         }
 
-        let mut sample_duration = Vec::new();
-        let mut sample_size = Vec::new();
-        let mut sample_flags = Vec::new();
+        // A degenerate trun can claim a sample_count in the billions even when none of the
+        // per-sample fields are present, in which case the loop below would still have to spin
+        // through every iteration doing nothing. Reject that outright, independent of the
+        // byte-accurate check below.
+        const MAX_SAMPLE_COUNT: u32 = 1_000_000;
+
+        if sample_count > MAX_SAMPLE_COUNT {
+            return decode_error("isomp4 (trun): sample_count is unreasonably large");
+        }
+
+        // Each present per-sample field is a 4-byte value. Use this to validate sample_count
+        // against the number of bytes actually remaining in the atom, and to avoid pre-allocating
+        // based on a wildly exaggerated count.
+        let mut per_sample_len = 0u64;
+
+        if (flags & TrunAtom::SAMPLE_DURATION_PRESENT) != 0 {
+            per_sample_len += 4;
+        }
+        if (flags & TrunAtom::SAMPLE_SIZE_PRESENT) != 0 {
+            per_sample_len += 4;
+        }
+        if (flags & TrunAtom::SAMPLE_FLAGS_PRESENT) != 0 {
+            per_sample_len += 4;
+        }
+        if (flags & TrunAtom::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT) != 0 {
+            per_sample_len += 4;
+        }
+
+        if per_sample_len > 0 {
+            // Bytes consumed so far: the full-box version/flags (4), sample_count (4), and the
+            // optional data_offset and first_sample_flags fields (4 bytes each).
+            let mut header_len = 8;
+
+            if data_offset.is_some() {
+                header_len += 4;
+            }
+            if first_sample_flags.is_some() {
+                header_len += 4;
+            }
+
+            let remaining = header.data_len.saturating_sub(header_len);
+
+            if u64::from(sample_count) > remaining / per_sample_len {
+                return decode_error("isomp4 (trun): sample_count exceeds the size of the atom");
+            }
+        }
+
+        // Cap pre-allocation so a bogus sample_count can't force a huge upfront allocation; the
+        // size check above guarantees the real count (if valid) is reasonable.
+        const MAX_PREALLOCATED_SAMPLES: usize = 8 * 1024;
+        let prealloc_count = std::cmp::min(sample_count as usize, MAX_PREALLOCATED_SAMPLES);
+
+        let reserve_if = |present| if present { prealloc_count } else { 0 };
+
+        let mut sample_duration =
+            Vec::with_capacity(reserve_if((flags & TrunAtom::SAMPLE_DURATION_PRESENT) != 0));
+        let mut sample_size =
+            Vec::with_capacity(reserve_if((flags & TrunAtom::SAMPLE_SIZE_PRESENT) != 0));
+        let mut sample_flags =
+            Vec::with_capacity(reserve_if((flags & TrunAtom::SAMPLE_FLAGS_PRESENT) != 0));
+        let mut sample_composition_time_offset = Vec::with_capacity(reserve_if(
+            (flags & TrunAtom::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT) != 0,
+        ));
 
         let mut total_sample_size = 0;
         let mut total_sample_duration = 0;
 
-        // TODO: Apply a limit.
         for _ in 0..sample_count {
 
             if (flags & TrunAtom::SAMPLE_DURATION_PRESENT) != 0 {
@@ -127,18 +208,17 @@ impl Atom for TrunAtom {
                 sample_flags.push(reader.read_be_u32()?);
             }
 
-            // Ignoring composition time for now since it's a video thing...
             if (flags & TrunAtom::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT) != 0 {
-                // For version 0, this is a u32.
-                // For version 1, this is a i32.
-                let _ = reader.read_be_u32()?;
+                // In version 0, this is an unsigned offset. In version 1, it is a signed offset.
+                let cto = if version == 0 {
+                    i64::from(reader.read_be_u32()?)
+                } else {
+                    i64::from(bits::sign_extend_leq32_to_i32(reader.read_be_u32()?, 32))
+                };
+                sample_composition_time_offset.push(cto);
             }
         }
 
-        if (flags & TrunAtom::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT) != 0 {
-            warn!("ignoring sample composition time offsets.");
-        }
-
         Ok(TrunAtom {
             header,
             flags,
@@ -148,8 +228,120 @@ impl Atom for TrunAtom {
             sample_duration,
             sample_size,
             sample_flags,
+            sample_composition_time_offset,
             total_sample_size,
             total_sample_duration,
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::AtomType;
+
+    use symphonia_core::io::BufReader;
+
+    fn header(data_len: u64) -> AtomHeader {
+        AtomHeader { atype: AtomType::TrackFragmentRun, data_len }
+    }
+
+    #[test]
+    fn read_rejects_sample_count_above_the_hard_ceiling() {
+        // version = 0, flags = 0 (no per-sample fields at all), sample_count = u32::MAX.
+        let mut buf = [0, 0, 0, 0].to_vec();
+        buf.extend_from_slice(&u32::MAX.to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let result = TrunAtom::read(&mut reader, header(buf.len() as u64));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn read_rejects_sample_count_that_does_not_fit_in_the_atom() {
+        // flags = SAMPLE_SIZE_PRESENT (0x200), sample_count = 100, but the atom is only large
+        // enough to hold the 8-byte header, let alone 100 * 4 bytes of sample sizes.
+        let mut buf = [0, 0, 0x02, 0x00].to_vec();
+        buf.extend_from_slice(&100u32.to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let result = TrunAtom::read(&mut reader, header(buf.len() as u64));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn composition_time_offset_is_unsigned_in_version_0() {
+        // version = 0, flags = SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT (0x800), sample_count = 1.
+        let mut buf = [0, 0, 0x08, 0x00].to_vec();
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(&0xffff_fffeu32.to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let trun = TrunAtom::read(&mut reader, header(buf.len() as u64)).unwrap();
+
+        assert_eq!(trun.composition_time_offset(0), 0xffff_fffe);
+    }
+
+    #[test]
+    fn composition_time_offset_is_sign_extended_in_version_1() {
+        // version = 1, flags = SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT (0x800), sample_count = 1.
+        let mut buf = [1, 0, 0x08, 0x00].to_vec();
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(&(-2i32).to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let trun = TrunAtom::read(&mut reader, header(buf.len() as u64)).unwrap();
+
+        assert_eq!(trun.composition_time_offset(0), -2);
+    }
+
+    #[test]
+    fn read_parses_first_sample_flags_present() {
+        // flags = FIRST_SAMPLE_FLAGS_PRESENT (0x4), sample_count = 2, no other per-sample fields.
+        let mut buf = [0, 0, 0, 0x04].to_vec();
+        buf.extend_from_slice(&2u32.to_be_bytes());
+        buf.extend_from_slice(&0x0200_0000u32.to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let trun = TrunAtom::read(&mut reader, header(buf.len() as u64)).unwrap();
+
+        assert_eq!(trun.first_sample_flags, Some(0x0200_0000));
+        assert_eq!(trun.sample_flags(0, 0x0101_0000), 0x0200_0000);
+        assert_eq!(trun.sample_flags(1, 0x0101_0000), 0x0101_0000);
+    }
+
+    #[test]
+    fn read_rejects_first_sample_flags_and_sample_flags_both_present() {
+        // flags = FIRST_SAMPLE_FLAGS_PRESENT (0x4) | SAMPLE_FLAGS_PRESENT (0x400).
+        let mut buf = [0, 0, 0x04, 0x04].to_vec();
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(&0x0200_0000u32.to_be_bytes());
+
+        let mut reader = BufReader::new(&buf);
+        let result = TrunAtom::read(&mut reader, header(buf.len() as u64));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn sample_flags_uses_first_sample_flags_only_for_index_zero() {
+        let trun = TrunAtom {
+            header: header(0),
+            flags: 0,
+            data_offset: None,
+            sample_count: 2,
+            first_sample_flags: Some(0x0200_0000),
+            sample_duration: Vec::new(),
+            sample_size: Vec::new(),
+            sample_flags: Vec::new(),
+            sample_composition_time_offset: Vec::new(),
+            total_sample_size: 0,
+            total_sample_duration: 0,
+        };
+
+        assert_eq!(trun.sample_flags(0, 0x0101_0000), 0x0200_0000);
+        assert_eq!(trun.sample_flags(1, 0x0101_0000), 0x0101_0000);
+    }
+}